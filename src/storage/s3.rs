@@ -1,17 +1,101 @@
 use super::{Blob, StorageTransaction};
-use chrono::{DateTime, NaiveDateTime, Utc};
+use aws_sdk_s3::model::{CompletedMultipartUpload, CompletedPart, Delete, ObjectIdentifier};
+use aws_sdk_s3::types::ByteStream;
+use aws_sdk_s3::{Client as S3Client, Endpoint, Region};
+use chrono::{DateTime, TimeZone, Utc};
 use failure::Error;
 use futures_util::{
-    future::TryFutureExt,
-    stream::{FuturesUnordered, StreamExt},
+    pin_mut,
+    stream::{self, FuturesUnordered, Stream, StreamExt},
+    TryStreamExt,
 };
 use log::warn;
 use once_cell::sync::Lazy;
-use rusoto_core::region::Region;
-use rusoto_credential::DefaultCredentialsProvider;
-use rusoto_s3::{GetObjectRequest, PutObjectRequest, S3Client, S3};
-use std::{convert::TryInto, io::Write};
-use tokio::runtime::Runtime;
+use std::{
+    convert::TryInto,
+    io::{Read, Seek, Write},
+};
+
+/// The rest of the crate (the `Storage`/`StorageTransaction` facade and its
+/// callers) is still synchronous, so `S3Backend`'s public surface stays
+/// synchronous too: every `aws-sdk-s3` call runs on this background runtime
+/// and the public methods block on it.
+static S3_RUNTIME: Lazy<tokio::runtime::Runtime> =
+    Lazy::new(|| tokio::runtime::Runtime::new().expect("failed to start the S3 runtime"));
+
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    S3_RUNTIME.block_on(future)
+}
+
+/// Objects larger than this are uploaded via S3's multipart API instead of a
+/// single `PutObject`, both to stay under S3's single-PUT size ceiling and to
+/// avoid holding two copies of a huge blob in memory at once. Configurable
+/// via `S3_MULTIPART_THRESHOLD` (bytes) for testing.
+const DEFAULT_MULTIPART_THRESHOLD: usize = 5 * 1024 * 1024;
+
+fn multipart_threshold() -> usize {
+    std::env::var("S3_MULTIPART_THRESHOLD")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MULTIPART_THRESHOLD)
+        // `blob.content.chunks(n)` panics for `n == 0`, and `S3_MULTIPART_THRESHOLD=0`
+        // is a plausible misconfiguration rather than just a test knob.
+        .max(1)
+}
+
+/// GETs whose `Content-Length` is above this are streamed into a temporary
+/// file instead of a growing in-memory buffer, so a handful of concurrent
+/// big downloads can't exhaust RAM. Configurable via `S3_STREAMING_THRESHOLD`
+/// (bytes).
+const DEFAULT_STREAMING_THRESHOLD: usize = 32 * 1024 * 1024;
+
+fn streaming_threshold() -> usize {
+    std::env::var("S3_STREAMING_THRESHOLD")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_STREAMING_THRESHOLD)
+}
+
+/// Max keys requested per `ListObjectsV2` page in `list_prefix`. Defaults to
+/// S3's own cap; overridable via `S3_LIST_PAGE_SIZE` so tests can force
+/// pagination without uploading thousands of objects.
+const DEFAULT_LIST_PAGE_SIZE: i32 = 1000;
+
+fn list_page_size() -> i32 {
+    std::env::var("S3_LIST_PAGE_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_LIST_PAGE_SIZE)
+}
+
+/// Streams `body` into a temp file as it arrives rather than growing a
+/// single `Vec` in memory, returning the open file (rewound to the start)
+/// and the number of bytes written. The file is unlinked as soon as the
+/// returned `NamedTempFile` is dropped.
+async fn spill_to_disk(
+    body: &mut aws_sdk_s3::types::ByteStream,
+    max_size: usize,
+) -> Result<(tempfile::NamedTempFile, u64), Error> {
+    let mut file = tempfile::NamedTempFile::new()?;
+    let mut written = 0usize;
+
+    while let Some(data) = body.try_next().await? {
+        written += data.len();
+        if written > max_size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                crate::error::SizeLimitReached,
+            )
+            .into());
+        }
+        file.write_all(data.as_ref())?;
+    }
+
+    file.flush()?;
+    file.seek(std::io::SeekFrom::Start(0))?;
+
+    Ok((file, written as u64))
+}
 
 #[cfg(test)]
 mod test;
@@ -19,8 +103,6 @@ mod test;
 pub(crate) use test::TestS3;
 
 pub(crate) static S3_BUCKET_NAME: &str = "rust-docs-rs";
-pub(crate) static S3_RUNTIME: Lazy<Runtime> =
-    Lazy::new(|| Runtime::new().expect("Failed to create S3 runtime"));
 
 pub(crate) struct S3Backend {
     pub client: S3Client,
@@ -35,41 +117,207 @@ impl S3Backend {
         }
     }
 
+    /// Fetches `path` as a fully-buffered [`Blob`], the contract every
+    /// caller outside this module already relies on. Objects streamed to
+    /// disk under the hood (see [`Self::get_stream`]) are read back into
+    /// memory here, so this is the wrong entry point for callers that want
+    /// to stream an oversized object straight through without buffering it;
+    /// use `get_stream` for that instead.
     pub(super) fn get(&self, path: &str, max_size: usize) -> Result<Blob, Error> {
-        S3_RUNTIME.handle().block_on(async {
+        match self.get_stream(path, max_size)? {
+            GetResult::Blob(blob) => Ok(blob),
+            GetResult::File(file_blob) => file_blob.into_blob(),
+        }
+    }
+
+    /// Like [`Self::get`], but objects larger than `streaming_threshold()`
+    /// come back as a disk-backed [`FileBlob`] instead of being buffered
+    /// into memory, so a caller that can stream the response straight
+    /// through doesn't have to hold the whole object in RAM.
+    pub(super) fn get_stream(&self, path: &str, max_size: usize) -> Result<GetResult, Error> {
+        block_on(self.get_async(path, max_size))
+    }
+
+    async fn get_async(&self, path: &str, max_size: usize) -> Result<GetResult, Error> {
+        let res = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(path)
+            .send()
+            .await?;
+
+        let content_length = res
+            .content_length()
+            .and_then(|l| l.try_into().ok())
+            .unwrap_or(0);
+        let mut body = res.body;
+
+        if content_length > streaming_threshold() {
+            let (file, len) = spill_to_disk(&mut body, max_size).await?;
+            let date_updated = res
+                .last_modified()
+                .ok_or_else(|| {
+                    failure::err_msg("Received a response from S3 with no last-modified")
+                })
+                .map(to_chrono_utc)?;
+
+            return Ok(GetResult::File(FileBlob {
+                path: path.into(),
+                mime: res
+                    .content_type()
+                    .ok_or_else(|| {
+                        failure::err_msg("Received a response from S3 with no content-type")
+                    })?
+                    .to_string(),
+                date_updated,
+                content_encoding: res.content_encoding().map(str::to_string),
+                file,
+                len,
+            }));
+        }
+
+        let mut content = crate::utils::sized_buffer::SizedBuffer::new(max_size);
+        content.reserve(content_length);
+        while let Some(data) = body.try_next().await? {
+            content.write_all(data.as_ref())?;
+        }
+
+        let date_updated = res
+            .last_modified()
+            .ok_or_else(|| failure::err_msg("Received a response from S3 with no last-modified"))
+            .map(to_chrono_utc)?;
+        let compression = res.content_encoding().and_then(|s| s.parse().ok());
+
+        Ok(GetResult::Blob(Blob {
+            path: path.into(),
+            mime: res
+                .content_type()
+                .ok_or_else(|| failure::err_msg("Received a response from S3 with no content-type"))?
+                .to_string(),
+            date_updated,
+            content: content.into_inner(),
+            compression,
+        }))
+    }
+
+    pub(super) fn get_range(
+        &self,
+        path: &str,
+        range: std::ops::Range<u64>,
+        max_size: usize,
+    ) -> Result<GetRangeResult, Error> {
+        // An inverted range (`start > end`) isn't a span at all; don't let it
+        // silently saturate to "zero bytes requested" and come back looking
+        // like a successful empty response.
+        if range.start > range.end {
+            return Err(failure::err_msg(format!(
+                "invalid range: start ({}) is after end ({})",
+                range.start, range.end
+            )));
+        }
+
+        // Reject spans larger than `max_size` up front instead of waiting for the
+        // transfer to run long enough to trip the cap inside `SizedBuffer`.
+        let requested_len = range.end.saturating_sub(range.start) as usize;
+        if requested_len > max_size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                crate::error::SizeLimitReached,
+            )
+            .into());
+        }
+
+        block_on(async {
+            // `bytes=start-end` is an inclusive range, so there's no way to ask
+            // S3 for zero bytes with it: `bytes=0-0` actually requests one byte.
+            // Fetch just the metadata instead of translating a zero-length span
+            // into a byte-range request that doesn't mean what it looks like.
+            if requested_len == 0 {
+                let res = self
+                    .client
+                    .head_object()
+                    .bucket(&self.bucket)
+                    .key(path)
+                    .send()
+                    .await?;
+
+                let date_updated = res
+                    .last_modified()
+                    .ok_or_else(|| {
+                        failure::err_msg("Received a response from S3 with no last-modified")
+                    })
+                    .map(to_chrono_utc)?;
+                let compression = res.content_encoding().and_then(|s| s.parse().ok());
+                let total_size = res.content_length().and_then(|l| l.try_into().ok());
+
+                return Ok(GetRangeResult {
+                    blob: Blob {
+                        path: path.into(),
+                        mime: res
+                            .content_type()
+                            .ok_or_else(|| {
+                                failure::err_msg(
+                                    "Received a response from S3 with no content-type",
+                                )
+                            })?
+                            .to_string(),
+                        date_updated,
+                        content: Vec::new(),
+                        compression,
+                    },
+                    total_size,
+                });
+            }
+
             let res = self
                 .client
-                .get_object(GetObjectRequest {
-                    bucket: self.bucket.to_string(),
-                    key: path.into(),
-                    ..Default::default()
-                })
+                .get_object()
+                .bucket(&self.bucket)
+                .key(path)
+                .range(format!(
+                    "bytes={}-{}",
+                    range.start,
+                    range.end.saturating_sub(1)
+                ))
+                .send()
                 .await?;
 
-            let mut content = crate::utils::sized_buffer::SizedBuffer::new(max_size);
+            let mut content = crate::utils::sized_buffer::SizedBuffer::new(requested_len);
             content.reserve(
-                res.content_length
+                res.content_length()
                     .and_then(|l| l.try_into().ok())
                     .unwrap_or(0),
             );
 
-            let mut body = res
-                .body
-                .ok_or_else(|| failure::err_msg("Received a response from S3 with no body"))?;
-
-            while let Some(data) = body.next().await.transpose()? {
+            let mut body = res.body;
+            while let Some(data) = body.try_next().await? {
                 content.write_all(data.as_ref())?;
             }
 
-            let date_updated = parse_timespec(&res.last_modified.unwrap())?;
-            let compression = res.content_encoding.and_then(|s| s.parse().ok());
+            let date_updated = res
+                .last_modified()
+                .ok_or_else(|| {
+                    failure::err_msg("Received a response from S3 with no last-modified")
+                })
+                .map(to_chrono_utc)?;
+            let compression = res.content_encoding().and_then(|s| s.parse().ok());
+            let total_size = parse_content_range_total(res.content_range());
 
-            Ok(Blob {
-                path: path.into(),
-                mime: res.content_type.unwrap(),
-                date_updated,
-                content: content.into_inner(),
-                compression,
+            Ok(GetRangeResult {
+                blob: Blob {
+                    path: path.into(),
+                    mime: res
+                        .content_type()
+                        .ok_or_else(|| {
+                            failure::err_msg("Received a response from S3 with no content-type")
+                        })?
+                        .to_string(),
+                    date_updated,
+                    content: content.into_inner(),
+                    compression,
+                },
+                total_size,
             })
         })
     }
@@ -77,6 +325,203 @@ impl S3Backend {
     pub(super) fn start_storage_transaction(&self) -> Result<S3StorageTransaction, Error> {
         Ok(S3StorageTransaction { s3: self })
     }
+
+    /// Iterates every key stored under `prefix`, paging through `ListObjectsV2`
+    /// continuation tokens lazily as the iterator is driven, so callers can
+    /// count or filter the (potentially bucket-wide) key list without
+    /// holding it all in memory at once. Each `next()` call blocks on at most
+    /// one `ListObjectsV2` request.
+    pub(super) fn list_prefix<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> impl Iterator<Item = Result<String, Error>> + 'a {
+        let mut stream = Box::pin(self.list_prefix_async(prefix));
+        std::iter::from_fn(move || block_on(stream.next()))
+    }
+
+    fn list_prefix_async<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> impl Stream<Item = Result<String, Error>> + 'a {
+        struct PageState {
+            keys: std::vec::IntoIter<String>,
+            continuation_token: Option<String>,
+            exhausted: bool,
+        }
+
+        let state = PageState {
+            keys: Vec::new().into_iter(),
+            continuation_token: None,
+            exhausted: false,
+        };
+
+        stream::try_unfold(state, move |mut state| async move {
+            loop {
+                if let Some(key) = state.keys.next() {
+                    return Ok(Some((key, state)));
+                }
+
+                if state.exhausted {
+                    return Ok(None);
+                }
+
+                let res = self
+                    .client
+                    .list_objects_v2()
+                    .bucket(&self.bucket)
+                    .prefix(prefix)
+                    .max_keys(list_page_size())
+                    .set_continuation_token(state.continuation_token.take())
+                    .send()
+                    .await?;
+
+                state.exhausted = res.is_truncated() != Some(true);
+                state.continuation_token = res.next_continuation_token().map(String::from);
+                state.keys = res
+                    .contents()
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|obj| obj.key().map(String::from))
+                    .collect::<Vec<_>>()
+                    .into_iter();
+            }
+        })
+    }
+
+    /// Deletes every object stored under `prefix`, used to reclaim storage
+    /// for yanked crates or superseded nightly docs.
+    pub(super) fn delete_prefix(&self, prefix: &str) -> Result<(), Error> {
+        block_on(async {
+            let keys = self.list_prefix_async(prefix);
+            pin_mut!(keys);
+
+            let mut chunk = Vec::with_capacity(1000);
+            let mut more = true;
+
+            while more {
+                chunk.clear();
+                while chunk.len() < 1000 {
+                    match keys.next().await {
+                        Some(key) => chunk.push(key?),
+                        None => {
+                            more = false;
+                            break;
+                        }
+                    }
+                }
+
+                if chunk.is_empty() {
+                    break;
+                }
+
+                let res = self
+                    .client
+                    .delete_objects()
+                    .bucket(&self.bucket)
+                    .delete(
+                        Delete::builder()
+                            .set_objects(Some(
+                                chunk
+                                    .iter()
+                                    .map(|key| ObjectIdentifier::builder().key(key).build())
+                                    .collect(),
+                            ))
+                            .quiet(true)
+                            .build(),
+                    )
+                    .send()
+                    .await?;
+
+                if let Some(err) = delete_objects_error(prefix, res.errors().unwrap_or_default()) {
+                    return Err(err);
+                }
+            }
+
+            Ok(())
+        })
+    }
+}
+
+/// Turns a non-empty `DeleteObjects` error list into a single `Error`,
+/// logging each failed key individually so operators can see exactly which
+/// keys survived a `delete_prefix` call instead of just "some deletes
+/// failed".
+fn delete_objects_error(prefix: &str, errors: &[aws_sdk_s3::model::Error]) -> Option<Error> {
+    if errors.is_empty() {
+        return None;
+    }
+
+    for err in errors {
+        log::error!(
+            "failed to delete {:?} while deleting prefix {:?}: {:?}",
+            err.key(),
+            prefix,
+            err.message(),
+        );
+    }
+
+    Some(failure::err_msg(format!(
+        "failed to delete {} object(s) while deleting prefix {:?}",
+        errors.len(),
+        prefix
+    )))
+}
+
+pub(super) struct GetRangeResult {
+    pub(super) blob: Blob,
+    /// Size of the full object, parsed out of the `Content-Range` response
+    /// header (`bytes start-end/total`), or taken from the `HeadObject`
+    /// `Content-Length` for a zero-length range, so callers can build their
+    /// own `Content-Range` header without a second request.
+    pub(super) total_size: Option<u64>,
+}
+
+/// Returned by [`S3Backend::get_stream`]. Objects at or below `streaming_threshold()`
+/// come back fully buffered as before; larger ones come back as a
+/// [`FileBlob`] backed by a temp file on disk instead, so the caller can
+/// stream it straight to the client rather than holding the whole object in
+/// memory.
+pub(super) enum GetResult {
+    Blob(Blob),
+    File(FileBlob),
+}
+
+/// Like [`Blob`], but `content` lives in a temp file rather than a `Vec<u8>`.
+/// The file is unlinked as soon as this value is dropped.
+pub(super) struct FileBlob {
+    pub(super) path: String,
+    pub(super) mime: String,
+    pub(super) date_updated: DateTime<Utc>,
+    /// Raw `Content-Encoding` header, kept unparsed here; `into_blob` parses
+    /// it into `Blob::compression`.
+    pub(super) content_encoding: Option<String>,
+    pub(super) file: tempfile::NamedTempFile,
+    pub(super) len: u64,
+}
+
+impl FileBlob {
+    /// Reads the backing temp file into memory, for callers that only know
+    /// how to deal with a fully-buffered [`Blob`].
+    fn into_blob(mut self) -> Result<Blob, Error> {
+        let mut content = Vec::with_capacity(self.len as usize);
+        self.file.read_to_end(&mut content)?;
+
+        Ok(Blob {
+            path: self.path,
+            mime: self.mime,
+            date_updated: self.date_updated,
+            content,
+            compression: self.content_encoding.as_deref().and_then(|s| s.parse().ok()),
+        })
+    }
+}
+
+fn parse_content_range_total(content_range: Option<&str>) -> Option<u64> {
+    content_range?.rsplit('/').next()?.parse().ok()
+}
+
+fn to_chrono_utc(dt: &aws_smithy_types::DateTime) -> DateTime<Utc> {
+    Utc.timestamp(dt.secs(), dt.subsec_nanos())
 }
 
 pub(super) struct S3StorageTransaction<'a> {
@@ -85,34 +530,32 @@ pub(super) struct S3StorageTransaction<'a> {
 
 impl<'a> StorageTransaction for S3StorageTransaction<'a> {
     fn store_batch(&mut self, mut batch: Vec<Blob>) -> Result<(), Error> {
-        S3_RUNTIME.handle().block_on(async {
+        block_on(async {
+            let s3 = self.s3;
+
             // Attempt to upload the batch 3 times
             for _ in 0..3 {
                 let mut futures = FuturesUnordered::new();
                 for blob in batch.drain(..) {
-                    futures.push(
-                        self.s3
-                            .client
-                            .put_object(PutObjectRequest {
-                                bucket: self.s3.bucket.to_string(),
-                                key: blob.path.clone(),
-                                body: Some(blob.content.clone().into()),
-                                content_type: Some(blob.mime.clone()),
-                                content_encoding: blob
-                                    .compression
-                                    .as_ref()
-                                    .map(|alg| alg.to_string()),
-                                ..Default::default()
-                            })
-                            .map_ok(|_| {
+                    futures.push(async move {
+                        let result = if blob.content.len() > multipart_threshold() {
+                            upload_multipart(s3, &blob).await
+                        } else {
+                            upload_single(s3, &blob).await
+                        };
+
+                        match result {
+                            Ok(()) => {
                                 crate::web::metrics::UPLOADED_FILES_TOTAL.inc_by(1);
-                            })
-                            .map_err(|err| {
+                                Ok(())
+                            }
+                            Err(err) => {
                                 log::error!("Failed to upload blob to S3: {:?}", err);
                                 // Reintroduce failed blobs for a retry
-                                blob
-                            }),
-                    );
+                                Err(blob)
+                            }
+                        }
+                    });
                 }
 
                 while let Some(result) = futures.next().await {
@@ -131,73 +574,234 @@ impl<'a> StorageTransaction for S3StorageTransaction<'a> {
             panic!("failed to upload 3 times, exiting");
         })
     }
+}
+
+async fn upload_single(s3: &S3Backend, blob: &Blob) -> Result<(), Error> {
+    s3.client
+        .put_object()
+        .bucket(&s3.bucket)
+        .key(&blob.path)
+        .body(ByteStream::from(blob.content.clone()))
+        .content_type(&blob.mime)
+        .set_content_encoding(blob.compression.as_ref().map(|alg| alg.to_string()))
+        .set_content_md5(content_md5_header(&blob.content))
+        .send()
+        .await?;
+
+    Ok(())
+}
+
+/// MinIO and some other S3-compatible test backends don't validate
+/// `Content-MD5`, and a few have been known to reject it outright, so
+/// sending it is opt-in rather than unconditional.
+fn content_md5_enabled() -> bool {
+    std::env::var_os("S3_CONTENT_MD5").is_some()
+}
+
+fn content_md5(content: &[u8]) -> String {
+    base64::encode(md5::compute(content).as_ref())
+}
+
+/// The `Content-MD5` value to send for `content`, or `None` when
+/// `S3_CONTENT_MD5` isn't set. Shared by `upload_single` and `upload_parts`
+/// so both the single-PUT and multipart paths gate on the same flag.
+fn content_md5_header(content: &[u8]) -> Option<String> {
+    content_md5_enabled().then(|| content_md5(content))
+}
 
-    fn complete(self: Box<Self>) -> Result<(), Error> {
-        Ok(())
+async fn upload_multipart(s3: &S3Backend, blob: &Blob) -> Result<(), Error> {
+    let create = s3
+        .client
+        .create_multipart_upload()
+        .bucket(&s3.bucket)
+        .key(&blob.path)
+        .content_type(&blob.mime)
+        .set_content_encoding(blob.compression.as_ref().map(|alg| alg.to_string()))
+        .send()
+        .await?;
+
+    let upload_id = create
+        .upload_id()
+        .ok_or_else(|| failure::err_msg("missing upload_id in CreateMultipartUpload response"))?
+        .to_string();
+
+    match upload_parts(s3, blob, &upload_id).await {
+        Ok(parts) => {
+            s3.client
+                .complete_multipart_upload()
+                .bucket(&s3.bucket)
+                .key(&blob.path)
+                .upload_id(&upload_id)
+                .multipart_upload(
+                    CompletedMultipartUpload::builder()
+                        .set_parts(Some(parts))
+                        .build(),
+                )
+                .send()
+                .await?;
+            Ok(())
+        }
+        Err(err) => {
+            // Don't leave an orphaned (and billed-for) multipart upload behind.
+            if let Err(abort_err) = s3
+                .client
+                .abort_multipart_upload()
+                .bucket(&s3.bucket)
+                .key(&blob.path)
+                .upload_id(&upload_id)
+                .send()
+                .await
+            {
+                log::error!("Failed to abort multipart upload: {:?}", abort_err);
+            }
+            Err(err)
+        }
     }
 }
 
-fn parse_timespec(mut raw: &str) -> Result<DateTime<Utc>, Error> {
-    raw = raw.trim_end_matches(" GMT");
+async fn upload_parts(
+    s3: &S3Backend,
+    blob: &Blob,
+    upload_id: &str,
+) -> Result<Vec<CompletedPart>, Error> {
+    let mut parts = Vec::new();
+
+    for (i, chunk) in blob.content.chunks(multipart_threshold()).enumerate() {
+        let part_number = (i + 1) as i32;
+        let res = s3
+            .client
+            .upload_part()
+            .bucket(&s3.bucket)
+            .key(&blob.path)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(ByteStream::from(chunk.to_vec()))
+            .set_content_md5(content_md5_header(chunk))
+            .send()
+            .await?;
+
+        let e_tag = res
+            .e_tag()
+            .ok_or_else(|| failure::err_msg("missing ETag in UploadPart response"))?;
+
+        parts.push(
+            CompletedPart::builder()
+                .part_number(part_number)
+                .e_tag(e_tag)
+                .build(),
+        );
+    }
 
-    Ok(DateTime::from_utc(
-        NaiveDateTime::parse_from_str(raw, "%a, %d %b %Y %H:%M:%S")?,
-        Utc,
-    ))
+    Ok(parts)
 }
 
-pub(crate) fn s3_client() -> Option<S3Client> {
+pub(crate) async fn s3_client() -> Option<S3Client> {
     // If AWS keys aren't configured, then presume we should use the DB exclusively
     // for file storage.
     if std::env::var_os("AWS_ACCESS_KEY_ID").is_none() && std::env::var_os("FORCE_S3").is_none() {
         return None;
     }
 
-    let creds = match DefaultCredentialsProvider::new() {
-        Ok(creds) => creds,
-        Err(err) => {
-            warn!("failed to retrieve AWS credentials: {}", err);
-            return None;
-        }
-    };
-
-    Some(S3Client::new_with(
-        rusoto_core::request::HttpClient::new().unwrap(),
-        creds,
-        std::env::var("S3_ENDPOINT")
-            .ok()
-            .map(|e| Region::Custom {
-                name: std::env::var("S3_REGION").unwrap_or_else(|_| "us-west-1".to_owned()),
-                endpoint: e,
-            })
-            .unwrap_or(Region::UsWest1),
-    ))
+    let mut config_loader = aws_config::from_env();
+
+    if let Ok(region) = std::env::var("S3_REGION") {
+        config_loader = config_loader.region(Region::new(region));
+    } else {
+        config_loader = config_loader.region(Region::new("us-west-1"));
+    }
+
+    if let Ok(endpoint) = std::env::var("S3_ENDPOINT") {
+        let endpoint = Endpoint::immutable(endpoint.parse().expect("invalid S3_ENDPOINT"));
+        config_loader = config_loader.endpoint_resolver(endpoint);
+    }
+
+    let shared_config = config_loader.load().await;
+    if shared_config.credentials_provider().is_none() {
+        warn!("failed to retrieve AWS credentials");
+        return None;
+    }
+
+    Some(S3Client::new(&shared_config))
 }
 
 #[cfg(test)]
 pub(crate) mod tests {
     use super::*;
     use crate::test::*;
-    use chrono::TimeZone;
+
+    /// `#[test]` functions run concurrently by default, but `content_md5_enabled`,
+    /// `multipart_threshold`, `streaming_threshold` and `list_page_size` all read
+    /// process-global env vars. Any test that sets one of these (directly or via
+    /// `EnvVarGuard`) must hold this lock for its duration, and so must any test
+    /// that exercises code reading them (uploads, downloads, listing), or it can
+    /// flakily observe another test's value.
+    static ENV_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    fn lock_env() -> std::sync::MutexGuard<'static, ()> {
+        ENV_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Sets an `S3_*` env var for the duration of the guard, restoring its
+    /// previous value (or removing it) on drop, so tests that flip these
+    /// flags don't leak state into other tests. Also holds `ENV_LOCK` so no
+    /// other test can observe the flipped value concurrently.
+    struct EnvVarGuard {
+        key: &'static str,
+        previous: Option<String>,
+        _lock: std::sync::MutexGuard<'static, ()>,
+    }
+
+    impl EnvVarGuard {
+        fn set(key: &'static str, value: &str) -> Self {
+            let lock = lock_env();
+            let previous = std::env::var(key).ok();
+            std::env::set_var(key, value);
+            EnvVarGuard {
+                key,
+                previous,
+                _lock: lock,
+            }
+        }
+    }
+
+    impl Drop for EnvVarGuard {
+        fn drop(&mut self) {
+            match self.previous.take() {
+                Some(value) => std::env::set_var(self.key, value),
+                None => std::env::remove_var(self.key),
+            }
+        }
+    }
 
     #[test]
-    fn test_parse_timespec() {
-        // Test valid conversions
-        assert_eq!(
-            parse_timespec("Thu, 1 Jan 1970 00:00:00 GMT").unwrap(),
-            Utc.ymd(1970, 1, 1).and_hms(0, 0, 0),
-        );
-        assert_eq!(
-            parse_timespec("Mon, 16 Apr 2018 04:33:50 GMT").unwrap(),
-            Utc.ymd(2018, 4, 16).and_hms(4, 33, 50),
-        );
+    fn test_multipart_threshold_clamps_zero() {
+        let _guard = EnvVarGuard::set("S3_MULTIPART_THRESHOLD", "0");
+        assert_eq!(multipart_threshold(), 1);
+    }
 
-        // Test invalid conversion
-        assert!(parse_timespec("foo").is_err());
+    #[test]
+    fn test_content_md5() {
+        assert_eq!(content_md5(b"Hello world!"), "hvsmnRkNLIX24EaM7KQqIA==");
+    }
+
+    #[test]
+    fn test_content_md5_header_respects_env_flag() {
+        let _lock = lock_env();
+        let content = b"Hello world!";
+
+        // Env vars are process-global; clear any leftover state from other
+        // tests before asserting the default (disabled) behavior.
+        std::env::remove_var("S3_CONTENT_MD5");
+        assert_eq!(content_md5_header(content), None);
+
+        std::env::set_var("S3_CONTENT_MD5", "1");
+        assert_eq!(content_md5_header(content), Some(content_md5(content)));
+        std::env::remove_var("S3_CONTENT_MD5");
     }
 
     #[test]
     fn test_get() {
+        let _lock = lock_env();
         wrapper(|env| {
             let blob = Blob {
                 path: "dir/foo.txt".into(),
@@ -224,6 +828,7 @@ pub(crate) mod tests {
 
     #[test]
     fn test_get_too_big() {
+        let _lock = lock_env();
         const MAX_SIZE: usize = 1024;
 
         wrapper(|env| {
@@ -263,8 +868,113 @@ pub(crate) mod tests {
         })
     }
 
+    #[test]
+    fn test_get_streams_large_objects_to_disk() {
+        let _threshold = EnvVarGuard::set("S3_STREAMING_THRESHOLD", "16");
+
+        wrapper(|env| {
+            let content = b"This content is longer than the 16-byte threshold above".to_vec();
+            let blob = Blob {
+                path: "large-blob.bin".into(),
+                mime: "text/plain".into(),
+                date_updated: Utc::now(),
+                content: content.clone(),
+                compression: None,
+            };
+
+            let s3 = env.s3();
+            s3.upload(vec![blob]).unwrap();
+
+            s3.with_client(|client| {
+                let result = client.get_stream("large-blob.bin", content.len()).unwrap();
+                let mut file_blob = match result {
+                    GetResult::File(file_blob) => file_blob,
+                    GetResult::Blob(_) => panic!("expected a file-backed blob"),
+                };
+
+                assert_eq!(file_blob.len, content.len() as u64);
+                let mut read_back = Vec::new();
+                file_blob.file.read_to_end(&mut read_back).unwrap();
+                assert_eq!(read_back, content);
+            });
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_get_reads_disk_backed_objects_back_into_a_blob() {
+        let _threshold = EnvVarGuard::set("S3_STREAMING_THRESHOLD", "16");
+
+        wrapper(|env| {
+            let content = b"This content is longer than the 16-byte threshold above".to_vec();
+            let blob = Blob {
+                path: "large-blob.bin".into(),
+                mime: "text/plain".into(),
+                date_updated: Utc::now(),
+                content: content.clone(),
+                compression: None,
+            };
+
+            let s3 = env.s3();
+            s3.upload(vec![blob]).unwrap();
+
+            // `get` should still hand back a plain `Blob`, even though this
+            // object is large enough to be streamed to disk internally.
+            s3.with_client(|client| {
+                let blob = client.get("large-blob.bin", content.len()).unwrap();
+                assert_eq!(blob.content, content);
+            });
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_get_range() {
+        let _lock = lock_env();
+        wrapper(|env| {
+            let blob = Blob {
+                path: "foo.txt".into(),
+                mime: "text/plain".into(),
+                date_updated: Utc::now(),
+                content: b"Hello world!".to_vec(),
+                compression: None,
+            };
+
+            let s3 = env.s3();
+            s3.upload(vec![blob.clone()]).unwrap();
+
+            s3.with_client(|client| {
+                let result = client.get_range("foo.txt", 0..5, 1024).unwrap();
+                assert_eq!(result.blob.content, b"Hello");
+
+                assert!(
+                    client
+                        .get_range("foo.txt", 0..5, 2)
+                        .unwrap_err()
+                        .downcast_ref::<std::io::Error>()
+                        .and_then(|io| io.get_ref())
+                        .and_then(|err| err.downcast_ref::<crate::error::SizeLimitReached>())
+                        .is_some()
+                );
+
+                // A zero-length range must come back with zero bytes of
+                // content rather than the single byte `bytes=0-0` would ask
+                // S3 for.
+                let empty = client.get_range("foo.txt", 0..0, 1024).unwrap();
+                assert_eq!(empty.blob.content, b"");
+
+                assert!(client.get_range("foo.txt", 5..0, 1024).is_err());
+            });
+
+            Ok(())
+        })
+    }
+
     #[test]
     fn test_store() {
+        let _lock = lock_env();
         wrapper(|env| {
             let s3 = env.s3();
             let names = [
@@ -295,6 +1005,73 @@ pub(crate) mod tests {
         })
     }
 
+    #[test]
+    fn test_store_multipart() {
+        let _threshold = EnvVarGuard::set("S3_MULTIPART_THRESHOLD", "10");
+
+        wrapper(|env| {
+            // Larger than the 10-byte threshold above and not an exact
+            // multiple of it, so the upload exercises several full parts
+            // plus a smaller final one.
+            let blob = Blob {
+                path: "multipart.bin".into(),
+                mime: "text/plain".into(),
+                date_updated: Utc::now(),
+                content: b"This blob is larger than the multipart threshold.".to_vec(),
+                compression: None,
+            };
+
+            let s3 = env.s3();
+            s3.upload(vec![blob.clone()]).unwrap();
+            s3.assert_blob(&blob, "multipart.bin");
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_list_prefix_pagination() {
+        let _page_size = EnvVarGuard::set("S3_LIST_PAGE_SIZE", "1");
+
+        wrapper(|env| {
+            let blobs: Vec<_> = ["list/a", "list/b", "list/c"]
+                .iter()
+                .map(|&path| Blob {
+                    path: path.into(),
+                    mime: "text/plain".into(),
+                    date_updated: Utc::now(),
+                    content: b"x".to_vec(),
+                    compression: None,
+                })
+                .collect();
+
+            let s3 = env.s3();
+            s3.upload(blobs.clone()).unwrap();
+
+            s3.with_client(|client| {
+                let mut keys: Vec<String> =
+                    client.list_prefix("list/").collect::<Result<_, _>>().unwrap();
+                keys.sort();
+                assert_eq!(keys, vec!["list/a", "list/b", "list/c"]);
+            });
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_delete_objects_error_surfaces_failed_keys() {
+        assert!(delete_objects_error("prefix/", &[]).is_none());
+
+        let errors = vec![aws_sdk_s3::model::Error::builder()
+            .key("prefix/stuck-key")
+            .message("AccessDenied")
+            .build()];
+
+        let err = delete_objects_error("prefix/", &errors).unwrap();
+        assert!(err.to_string().contains("1 object"));
+    }
+
     // NOTE: trying to upload a file ending with `/` will behave differently in test and prod.
     // NOTE: On s3, it will succeed and create a file called `/`.
     // NOTE: On min.io, it will fail with 'Object name contains unsupported characters.'